@@ -0,0 +1,42 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// WS2812B timing targets (datasheet nominal values), in nanoseconds.
+const T0H_NS: f64 = 350.0;
+const T1H_NS: f64 = 700.0;
+
+// The leading `st` that drives the line high costs 2 clock ticks on AVR;
+// everything past that is padding supplied by `nop`s.
+const STORE_TICKS: i64 = 2;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=AVR_CPU_FREQUENCY");
+
+    let cpu_frequency: f64 = env::var("AVR_CPU_FREQUENCY")
+        .ok()
+        .and_then(|freq| freq.parse().ok())
+        .unwrap_or(16_000_000.0);
+
+    emit_nops("send_0_high_nops.s", nops_for(T0H_NS, cpu_frequency));
+    emit_nops("send_1_high_nops.s", nops_for(T1H_NS, cpu_frequency));
+}
+
+/// Converts a target high-phase duration into the number of `nop`s needed
+/// to pad out the `st` that already consumes `STORE_TICKS`.
+fn nops_for(phase_ns: f64, cpu_frequency: f64) -> i64 {
+    let ticks = (phase_ns * cpu_frequency / 1e9).round() as i64;
+    (ticks - STORE_TICKS).max(0)
+}
+
+/// Writes `count` lines of `nop` to a file under `OUT_DIR` meant to be
+/// spliced into an `asm!` invocation as a single string via
+/// `include_str!` (an `asm!` template argument, not a token sequence).
+fn emit_nops(file_name: &str, count: i64) {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dest = Path::new(&out_dir).join(file_name);
+
+    let body = "nop\n".repeat(count as usize);
+
+    fs::write(dest, body).unwrap();
+}
@@ -3,12 +3,28 @@
 
 //! This crate implements a WS2812B driver
 //!
-//! You can get started by using the `WS2812B` struct.
+//! You can get started by using the `WS2812B` struct. If you need to
+//! drive the strip through a HAL other than `avr-pin`, or from anything
+//! else that only implements `embedded_hal::digital::OutputPin`, use
+//! `WS2812BPin` instead. The crate as a whole builds only for AVR
+//! targets: it uses AVR inline assembly and depends on `avr-delay` and
+//! `avr-pin` unconditionally.
+//!
+//! Both drivers are generic over `PixelFormat`, so they work equally
+//! well with `RGB` (WS2812B, 3 bytes/pixel) and `RGBW` (SK6812, 4
+//! bytes/pixel) buffers.
+//!
+//! Pulse timing is computed at build time from the `AVR_CPU_FREQUENCY`
+//! environment variable (defaulting to 16 MHz if unset), so `send_0`/
+//! `send_1` produce in-spec waveforms on 8 MHz, 16 MHz and 20 MHz parts
+//! alike. See `build.rs`.
 
 use core::arch::asm;
 
 use avr_delay::delay_us;
 use avr_pin::{Pin, DD};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
 
 /// Represents a WS2812B data line.
 #[repr(packed)]
@@ -33,58 +49,153 @@ impl RGB {
     }
 }
 
-/// Sends a timed WS2812B pulse representing a 0 to a given `Pin`.
+impl PixelFormat for RGB {
+    fn to_bytes(&self) -> PixelBytes {
+        PixelBytes::Rgb((*self).to_bytes())
+    }
+}
+
+/// Describes an SK6812 RGBW pixel color state.
+#[repr(packed)]
+#[derive(Clone, Copy)]
+pub struct RGBW {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub w: u8,
+}
+
+impl RGBW {
+    /// Returns `Self` as bytes in protocol-defined order.
+    pub fn to_bytes(self) -> [u8; 4] {
+        [self.g, self.r, self.b, self.w]
+    }
+}
+
+impl PixelFormat for RGBW {
+    fn to_bytes(&self) -> PixelBytes {
+        PixelBytes::Rgbw((*self).to_bytes())
+    }
+}
+
+/// The wire-order bytes of a single pixel, sized for either a 3-byte
+/// (RGB) or 4-byte (RGBW) frame.
+pub enum PixelBytes {
+    Rgb([u8; 3]),
+    Rgbw([u8; 4]),
+}
+
+impl PixelBytes {
+    /// Returns the pixel's bytes in the order they must be shifted out.
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            PixelBytes::Rgb(bytes) => bytes,
+            PixelBytes::Rgbw(bytes) => bytes,
+        }
+    }
+}
+
+/// A pixel color format that can be serialized to wire-order bytes.
+///
+/// Implement this for a color type to drive `WS2812B`/`WS2812BPin` with
+/// it; `RGB` (3-byte, WS2812B) and `RGBW` (4-byte, SK6812) implement it
+/// out of the box.
+pub trait PixelFormat {
+    /// Returns this pixel's channels in the order they must be shifted
+    /// out on the data line.
+    fn to_bytes(&self) -> PixelBytes;
+}
+
+impl<T: PixelFormat> PixelFormat for &T {
+    fn to_bytes(&self) -> PixelBytes {
+        (**self).to_bytes()
+    }
+}
+
+/// Runs `f` with interrupts disabled, restoring the prior state of the
+/// SREG interrupt-enable bit afterwards rather than unconditionally
+/// re-enabling interrupts.
+///
+/// This guards timing-critical sections (such as frame transmission)
+/// against an ISR firing mid-pulse and stretching a phase out of spec.
+fn with_critical_section<F: FnOnce() -> R, R>(f: F) -> R {
+    let sreg: u8;
+    unsafe {
+        asm!("in {sreg}, 0x3f", sreg = out(reg) sreg);
+        asm!("cli");
+    }
+    let result = f();
+    unsafe {
+        asm!("out 0x3f, {sreg}", sreg = in(reg) sreg);
+    }
+    result
+}
+
+/// Exposes the raw I/O register address and bitmask behind a pin.
+///
+/// `send_0`/`send_1` are written against this trait rather than the
+/// concrete `avr_pin::Pin` type so the fast, cycle-counted AVR path
+/// isn't hard-wired to `avr-pin`; any type that can hand back a PORT
+/// address and mask can drive it.
+pub trait RawPin {
+    /// The address of the PORT register this pin toggles.
+    fn port(&self) -> *mut u8;
+    /// The bitmask of this pin within its PORT register.
+    fn mask(&self) -> u8;
+}
+
+impl RawPin for Pin {
+    fn port(&self) -> *mut u8 {
+        self.port
+    }
+    fn mask(&self) -> u8 {
+        self.mask
+    }
+}
+
+/// Sends a timed WS2812B pulse representing a 0 to a given `RawPin`.
+///
+/// The high-phase `nop` padding is generated by `build.rs` from
+/// `AVR_CPU_FREQUENCY`, so the pulse stays in spec across clock speeds.
 ///
 /// # Safety
 ///
-/// Since the `Pin` struct can be constructed by the user,
-/// there is no guarantee that the I/O register addresses
-/// are valid. Please ensure validity of self, ideally by
-/// avoiding manual generation of `Pin`.
-pub unsafe fn send_0(pin: &Pin) {
-    let high: u8 = *pin.port | pin.mask;
-    let low: u8 = *pin.port & !pin.mask;
+/// Since a `RawPin` impl can be constructed by the user, there is no
+/// guarantee that the I/O register address it reports is valid. Please
+/// ensure validity of self, ideally by avoiding manual `RawPin` impls.
+pub unsafe fn send_0<P: RawPin>(pin: &P) {
+    let port = pin.port();
+    let high: u8 = *port | pin.mask();
+    let low: u8 = *port & !pin.mask();
     asm!(
-        // 6 TICKS TOTAL
         "st {port}, {high}", // 2 TICKS
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
+        include_str!(concat!(env!("OUT_DIR"), "/send_0_high_nops.s")),
         "st {port}, {low}", // 2 TICKS
-        port = in(reg_ptr) pin.port,
+        port = in(reg_ptr) port,
         high = in(reg) high,
         low = in(reg) low,
     );
 }
 
-/// Sends a timed WS2812B pulse representing a 1 to a given `Pin`.
+/// Sends a timed WS2812B pulse representing a 1 to a given `RawPin`.
+///
+/// The high-phase `nop` padding is generated by `build.rs` from
+/// `AVR_CPU_FREQUENCY`, so the pulse stays in spec across clock speeds.
 ///
 /// # Safety
 ///
-/// Since the `Pin` struct can be constructed by the user,
-/// there is no guarantee that the I/O register addresses
-/// are valid. Please ensure validity of self, ideally by
-/// avoiding manual generation of `Pin`.
-pub unsafe fn send_1(pin: &Pin) {
-    let high: u8 = *pin.port | pin.mask;
-    let low: u8 = *pin.port & !pin.mask;
+/// Since a `RawPin` impl can be constructed by the user, there is no
+/// guarantee that the I/O register address it reports is valid. Please
+/// ensure validity of self, ideally by avoiding manual `RawPin` impls.
+pub unsafe fn send_1<P: RawPin>(pin: &P) {
+    let port = pin.port();
+    let high: u8 = *port | pin.mask();
+    let low: u8 = *port & !pin.mask();
     asm!(
-        // 13 TICKS TOTAL
         "st {port}, {high}", // 2 TICKS
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
-        "nop", // 1 TICK
+        include_str!(concat!(env!("OUT_DIR"), "/send_1_high_nops.s")),
         "st {port}, {low}", // 2 TICKS
-        port = in(reg_ptr) pin.port,
+        port = in(reg_ptr) port,
         high = in(reg) high,
         low = in(reg) low,
     );
@@ -114,18 +225,71 @@ impl WS2812B {
     }
     /// Sends a pixel array to the WS2812B.
     ///
+    /// The transmission runs inside a critical section (interrupts
+    /// disabled) so that an ISR can't stretch a pulse mid-byte and
+    /// corrupt every downstream pixel. Use
+    /// [`upload_without_critical_section`](Self::upload_without_critical_section)
+    /// if you already manage interrupts around the call yourself.
+    ///
+    /// Returns false if the pid in `Self` is invalid.
+    /// Otherwise, returns true.
+    pub fn upload<T: PixelFormat>(&self, buffer: &[T]) -> bool {
+        with_critical_section(|| self.upload_without_critical_section(buffer))
+    }
+    /// Sends a pixel array to the WS2812B without disabling interrupts.
+    ///
+    /// An interrupt firing mid-pulse will stretch that phase and corrupt
+    /// the rest of the frame; prefer [`upload`](Self::upload) unless you
+    /// are already bracketing the call with your own interrupt guard.
+    ///
+    /// Returns false if the pid in `Self` is invalid.
+    /// Otherwise, returns true.
+    pub fn upload_without_critical_section<T: PixelFormat>(&self, buffer: &[T]) -> bool {
+        self.upload_iter_without_critical_section(buffer.iter())
+    }
+    /// Streams pixels from an iterator instead of a materialized buffer.
+    ///
+    /// This avoids having to hold an entire framebuffer in RAM, which
+    /// matters on parts with only a couple KB of SRAM: colors can be
+    /// generated on the fly (gradients, animations, `HSV` conversions)
+    /// as they're shifted out. The transmission runs inside a critical
+    /// section, same as [`upload`](Self::upload); use
+    /// [`upload_iter_without_critical_section`](Self::upload_iter_without_critical_section)
+    /// if you manage interrupts yourself.
+    ///
+    /// `pixels.next()` only runs between pixels, after the previous
+    /// pixel's last bit has finished its fixed-duration low phase, so it
+    /// can't stretch a pulse's high phase. What it does stretch is the
+    /// idle gap between those two pixels, which shares the same ~50 µs
+    /// reset/latch threshold as the trailing delay after the whole
+    /// frame. Keep `next()` well under that budget (microseconds, not
+    /// a fixed sub-bit window) or the strip may latch mid-frame.
+    ///
     /// Returns false if the pid in `Self` is invalid.
     /// Otherwise, returns true.
-    pub fn upload(&self, buffer: &[RGB]) -> bool {
+    pub fn upload_iter<T: PixelFormat, I: IntoIterator<Item = T>>(&self, pixels: I) -> bool {
+        with_critical_section(|| self.upload_iter_without_critical_section(pixels))
+    }
+    /// Streams pixels from an iterator without disabling interrupts.
+    ///
+    /// See [`upload_iter`](Self::upload_iter) for the reset/latch budget
+    /// `pixels.next()` must stay within.
+    ///
+    /// Returns false if the pid in `Self` is invalid.
+    /// Otherwise, returns true.
+    pub fn upload_iter_without_critical_section<T: PixelFormat, I: IntoIterator<Item = T>>(
+        &self,
+        pixels: I,
+    ) -> bool {
         let pin: Pin = match Pin::from_pid(self.data_line_pid) {
             Some(pin) => pin,
             None => return false,
         };
-        for rgb in buffer {
-            for data in (*rgb).to_bytes() {
+        for pixel in pixels {
+            for data in pixel.to_bytes().as_slice() {
                 let mut mask: u8 = 0x80;
                 while mask != 0 {
-                    if data & mask > 0 {
+                    if *data & mask > 0 {
                         unsafe { send_1(&pin); }
                     } else {
                         unsafe { send_0(&pin); }
@@ -140,3 +304,71 @@ impl WS2812B {
     }
 }
 
+/// A WS2812B driver built on any `embedded_hal::digital::OutputPin`.
+///
+/// `WS2812B` drives the data line through raw register writes and an
+/// AVR-only cycle-counted delay, and is the default choice on AVR. This
+/// variant trades that precision for portability: it toggles the pin
+/// through the `embedded-hal` `OutputPin` trait and times phases through
+/// an injected `DelayNs`, so the same bit-banging logic also runs
+/// against HALs other than `avr-pin`. It does not make the crate
+/// portable: the crate still builds only for AVR targets.
+///
+/// Unlike `WS2812B`, this driver does not disable interrupts around the
+/// transmission, since there's no portable way to do so across arbitrary
+/// `embedded-hal` backends. On AVR (or any other target where an ISR
+/// can fire mid-transmission), an interrupt firing between bits can
+/// stretch a pulse out of spec and corrupt the frame; bracket `upload`/
+/// `upload_iter` with your own critical section if that's a risk for
+/// your target.
+pub struct WS2812BPin<P, D> {
+    pin: P,
+    delay: D,
+}
+
+impl<P: OutputPin, D: DelayNs> WS2812BPin<P, D> {
+    /// Creates a new driver wrapping an already-configured output pin
+    /// and the delay implementation to time its phases with.
+    pub const fn new(pin: P, delay: D) -> Self {
+        Self { pin, delay }
+    }
+    /// Sends a pixel array to the WS2812B.
+    ///
+    /// Returns false if setting the pin state ever fails.
+    /// Otherwise, returns true.
+    pub fn upload<T: PixelFormat>(&mut self, buffer: &[T]) -> bool {
+        self.upload_iter(buffer.iter())
+    }
+    /// Streams pixels from an iterator instead of a materialized buffer.
+    ///
+    /// See [`WS2812B::upload_iter`] for the reset/latch budget
+    /// `pixels.next()` must stay within.
+    ///
+    /// Returns false if setting the pin state ever fails.
+    /// Otherwise, returns true.
+    pub fn upload_iter<T, I>(&mut self, pixels: I) -> bool
+    where
+        T: PixelFormat,
+        I: IntoIterator<Item = T>,
+    {
+        for pixel in pixels {
+            for data in pixel.to_bytes().as_slice() {
+                let mut mask: u8 = 0x80;
+                while mask != 0 {
+                    if self.pin.set_high().is_err() {
+                        return false;
+                    }
+                    self.delay.delay_us(if *data & mask > 0 { 1 } else { 0 });
+                    if self.pin.set_low().is_err() {
+                        return false;
+                    }
+                    self.delay.delay_us(1);
+                    mask >>= 1;
+                }
+            }
+        }
+        self.delay.delay_us(50);
+        true
+    }
+}
+